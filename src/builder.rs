@@ -0,0 +1,152 @@
+use crate::{HitWindow, S3FIFO};
+
+///
+/// A builder for an S3FIFO that sizes each of the three queues independently.
+///
+/// Any queue that is not sized explicitly uses the same ratio as `S3FIFO::new`:
+/// small is 10% of the capacity, main is 90%, and ghost is the same size as main.
+///
+/// ```
+/// use s3_fifo::{S3FIFO, S3FIFOBuilder};
+///
+/// // A modest main queue with a large ghost to catch more re-requests
+/// let mut cache: S3FIFO<u32, String> = S3FIFOBuilder::new(1000)
+///     .main(500)
+///     .ghost(5000)
+///     .build();
+/// cache.put(1, "one".to_string());
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct S3FIFOBuilder {
+    capacity: usize,
+    small: Option<usize>,
+    main: Option<usize>,
+    ghost: Option<usize>,
+    decay_interval: usize,
+    max_reinsertions: usize,
+    hit_rate_window: usize,
+    small_freq_cap: u8,
+    main_freq_cap: u8,
+}
+
+impl S3FIFOBuilder {
+    ///
+    /// Create a new builder with a total capacity used to size any queue left unset.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        S3FIFOBuilder {
+            capacity,
+            small: None,
+            main: None,
+            ghost: None,
+            decay_interval: 0,
+            max_reinsertions: usize::MAX,
+            hit_rate_window: 0,
+            small_freq_cap: 3,
+            main_freq_cap: 3,
+        }
+    }
+
+    /// Set the number of items the small queue holds.
+    pub fn small(mut self, capacity: usize) -> Self {
+        self.small = Some(capacity);
+        self
+    }
+
+    /// Set the number of items the main queue holds.
+    pub fn main(mut self, capacity: usize) -> Self {
+        self.main = Some(capacity);
+        self
+    }
+
+    /// Set the number of keys the ghost queue holds.
+    pub fn ghost(mut self, capacity: usize) -> Self {
+        self.ghost = Some(capacity);
+        self
+    }
+
+    ///
+    /// Decay the frequency of every item after each `interval` calls to `put`.
+    ///
+    /// An interval of zero, the default, never decays automatically. See `S3FIFO::decay`.
+    ///
+    pub fn decay_interval(mut self, interval: usize) -> Self {
+        self.decay_interval = interval;
+        self
+    }
+
+    ///
+    /// Limit how many items main reinserts while looking for one to evict.
+    ///
+    /// Main reinserts any item with a nonzero frequency, so a full cache of hot items
+    /// can reinsert up to `main_freq_cap` times its length before evicting anything. Once the
+    /// limit is reached, the next item is evicted regardless of its frequency. This bounds
+    /// the latency of an eviction at the cost of sometimes evicting an item that was
+    /// still being used. Unlimited by default.
+    ///
+    pub fn max_reinsertions(mut self, max: usize) -> Self {
+        self.max_reinsertions = max;
+        self
+    }
+
+    ///
+    /// Track the outcome of the last `size` lookups for `S3FIFO::recent_hit_rate`.
+    ///
+    /// A size of zero, the default, tracks nothing.
+    ///
+    pub fn hit_rate_window(mut self, size: usize) -> Self {
+        self.hit_rate_window = size;
+        self
+    }
+
+    ///
+    /// Set the frequency at which reads of an item in small stop counting.
+    ///
+    /// Items in small are promoted when their frequency is above the promotion
+    /// threshold of 1, so the cap must be at least 2. The default of 3 matches
+    /// `S3FIFO::new`.
+    ///
+    pub fn small_freq_cap(mut self, cap: u8) -> Self {
+        self.small_freq_cap = cap;
+        self
+    }
+
+    ///
+    /// Set the frequency at which reads of an item in main stop counting.
+    ///
+    /// This bounds how many times main reinserts an item that is no longer read,
+    /// and a cap of 0 makes main a plain FIFO. Items promoted from small keep at most
+    /// this frequency. The default of 3 matches `S3FIFO::new`.
+    ///
+    pub fn main_freq_cap(mut self, cap: u8) -> Self {
+        self.main_freq_cap = cap;
+        self
+    }
+
+    ///
+    /// Create the S3FIFO cache.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either the small or main queue has a capacity of zero, or if the
+    /// frequency cap of small is not above the promotion threshold.
+    ///
+    pub fn build<K: PartialEq + Clone, V>(self) -> S3FIFO<K, V> {
+        let small = self.small.unwrap_or(self.capacity / 10);
+        let main = self.main.unwrap_or(self.capacity * 9 / 10);
+        let ghost = self.ghost.unwrap_or(main);
+        let mut cache = S3FIFO::with_capacities(small, main, ghost);
+        cache.decay_interval = self.decay_interval;
+        cache.max_reinsertions = self.max_reinsertions;
+        cache.hit_window = HitWindow::new(self.hit_rate_window);
+        cache.small_freq_cap = self.small_freq_cap.min(i8::MAX as u8) as i8;
+        assert!(
+            cache.promotion_threshold < cache.small_freq_cap,
+            "a small frequency cap of {} would never promote an item",
+            cache.small_freq_cap
+        );
+        cache.main_freq_cap = self.main_freq_cap.min(i8::MAX as u8) as i8;
+        cache
+    }
+}
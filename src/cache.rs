@@ -0,0 +1,74 @@
+use crate::S3FIFO;
+
+///
+/// The operations shared by cache implementations, so that call sites can be
+/// written once and benchmarked against different eviction policies.
+///
+/// ```
+/// use s3_fifo::{Cache, S3FIFO};
+///
+/// fn fill<C: Cache<u32, u32>>(cache: &mut C) -> usize {
+///     for i in 0..1000 {
+///         cache.put(i, i);
+///     }
+///     cache.len()
+/// }
+///
+/// assert!(fill(&mut S3FIFO::new(100)) <= 100);
+/// ```
+///
+pub trait Cache<K, V> {
+    ///
+    /// Read an item from the cache.
+    ///
+    /// This takes `&mut self` since most policies record the access,
+    /// so a read may change what is evicted next.
+    ///
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    /// Write an item to the cache, returning a value that was evicted to make room.
+    fn put(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Remove an item from the cache and return its value.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// The number of items in the cache.
+    fn len(&self) -> usize;
+
+    /// Whether the cache holds no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of items the cache holds before it evicts.
+    fn capacity(&self) -> usize;
+
+    /// Remove every item from the cache.
+    fn clear(&mut self);
+}
+
+impl<K: PartialEq + Clone, V> Cache<K, V> for S3FIFO<K, V> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        S3FIFO::get(self, key)
+    }
+
+    fn put(&mut self, key: K, value: V) -> Option<V> {
+        S3FIFO::put(self, key, value).1
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        S3FIFO::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        S3FIFO::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        S3FIFO::capacity(self)
+    }
+
+    fn clear(&mut self) {
+        S3FIFO::clear(self)
+    }
+}
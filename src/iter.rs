@@ -0,0 +1,108 @@
+use alloc::{collections::vec_deque, vec::Vec};
+use core::iter::Chain;
+
+use crate::{Item, S3FIFO};
+
+type Items<K, V> = vec_deque::IntoIter<Item<K, V>>;
+type ItemRefs<'a, K, V> = vec_deque::Iter<'a, Item<K, V>>;
+
+///
+/// An iterator that moves the entries out of an S3FIFO.
+///
+/// Entries in small are yielded before entries in main.
+/// Keys in ghost are dropped since they hold no values.
+///
+pub struct IntoIter<K, V> {
+    inner: Chain<Items<K, V>, Items<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|item| (item.key, item.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> IntoIterator for S3FIFO<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.small.into_iter().chain(self.main),
+        }
+    }
+}
+
+///
+/// An iterator over references to the entries of an S3FIFO.
+///
+/// Entries in small are yielded before entries in main.
+/// Iterating does not increment the frequency of any entry.
+///
+pub struct Iter<'a, K, V> {
+    inner: Chain<ItemRefs<'a, K, V>, ItemRefs<'a, K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|item| (&item.key, &item.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a S3FIFO<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V> S3FIFO<K, V> {
+    /// Iterate over the entries in the cache without changing their frequency.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.small.iter().chain(self.main.iter()),
+        }
+    }
+
+    ///
+    /// Iterate over the entries from the most to the least likely to be evicted next,
+    /// along with their frequencies.
+    ///
+    /// This is an approximation for debugging. Items in small that would be evicted
+    /// come first, oldest first. The rest are ordered by frequency, with items in main
+    /// ahead of items waiting in small for promotion, and the oldest first within each.
+    /// The actual order depends on how frequencies change as main reinserts items.
+    ///
+    pub fn iter_by_eviction_order(&self) -> impl Iterator<Item = (&K, &V, u8)> {
+        let (promoted, evicted): (Vec<_>, Vec<_>) = self
+            .small
+            .iter()
+            .rev()
+            .partition(|item| item.freq.get() > self.promotion_threshold);
+        let mut protected: Vec<&Item<K, V>> = self.main.iter().rev().chain(promoted).collect();
+        protected.sort_by_key(|item| item.freq.get());
+        evicted
+            .into_iter()
+            .chain(protected)
+            .map(|item| (&item.key, &item.value, item.freq.get() as u8))
+    }
+
+    /// Iterate over the keys in ghost, from the most to the least recently evicted.
+    pub fn ghost_keys(&self) -> impl Iterator<Item = &K> {
+        self.ghost.iter().map(|key| &key.key)
+    }
+}
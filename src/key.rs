@@ -1,31 +1,66 @@
+use core::fmt::{Display, LowerHex};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
 use std::collections::hash_map::DefaultHasher;
-use std::fmt::Display;
-use std::hash::{Hash, Hasher};
-use std::marker::PhantomData;
 
 ///
 /// S3FIFO requires a key. This is a
 /// convenience struct that will generate
 /// a key from a value that is Hash.
 ///
+/// Keys are compared by a fingerprint of the value, which is a 64-bit hash by default.
+/// Use `with_fingerprint` to supply a wider fingerprint, like a 128-bit hash,
+/// when collisions between 64-bit hashes are a concern.
+///
 #[derive(Clone)]
-pub struct S3FIFOKey<V: Hash> {
-    hash: u64,
+pub struct S3FIFOKey<V, F = u64> {
+    hash: F,
     _phantom: PhantomData<V>,
 }
 
-impl<V: Hash> PartialEq for S3FIFOKey<V> {
+impl<V, F: PartialEq> PartialEq for S3FIFOKey<V, F> {
     fn eq(&self, other: &Self) -> bool {
         self.hash == other.hash
     }
 }
 
+impl<V, F: Eq> Eq for S3FIFOKey<V, F> {}
+
+impl<V, F: Hash> Hash for S3FIFOKey<V, F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+impl<V, F> S3FIFOKey<V, F> {
+    ///
+    /// Create a new S3FIFOKey from the fingerprint that `fingerprint` computes for the value.
+    ///
+    /// ```
+    /// use s3_fifo::S3FIFOKey;
+    ///
+    /// let key = S3FIFOKey::with_fingerprint(&"foobar", |v| v.len() as u128);
+    /// assert!(key == S3FIFOKey::with_fingerprint(&"abcdef", |v| v.len() as u128));
+    /// ```
+    ///
+    pub fn with_fingerprint<G: FnOnce(&V) -> F>(value: &V, fingerprint: G) -> Self {
+        S3FIFOKey {
+            hash: fingerprint(value),
+            _phantom: PhantomData,
+        }
+    }
+}
+
 impl<V: Hash> S3FIFOKey<V> {
     ///
     /// Create a new S3FIFOKey from a value that is Hash.
     ///
     /// This will generate a hash from the value and act as the key.
     ///
+    /// Requires the `std` feature since it relies on `DefaultHasher`.
+    ///
+    #[cfg(feature = "std")]
     pub fn new(value: &V) -> Self {
         let mut hasher = DefaultHasher::new();
         value.hash(&mut hasher);
@@ -34,18 +69,86 @@ impl<V: Hash> S3FIFOKey<V> {
             _phantom: PhantomData,
         }
     }
+
+    ///
+    /// Create a new S3FIFOKey from a value that is Hash, using a fixed hash function.
+    ///
+    /// Unlike `new`, the hash only depends on the value and the seed, so it is stable
+    /// across processes for the same build and target. Use this when keys are compared
+    /// between processes or after a restart of the same binary. Hashes are not promised
+    /// to match across targets, Rust versions, or releases of this crate.
+    ///
+    pub fn new_seeded(value: &V, seed: u64) -> Self {
+        let mut hasher = StableHasher::new(seed);
+        value.hash(&mut hasher);
+        S3FIFOKey {
+            hash: hasher.finish(),
+            _phantom: PhantomData,
+        }
+    }
 }
 
-impl<V: Hash> std::fmt::Debug for S3FIFOKey<V> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///
+/// A 64-bit FNV-1a hasher that writes integers as little endian
+/// and `usize` as 64 bits so that hashes do not depend on endianness or pointer width.
+///
+struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    fn new(seed: u64) -> Self {
+        let mut hasher = StableHasher {
+            state: 0xcbf2_9ce4_8422_2325,
+        };
+        hasher.write_u64(seed);
+        hasher
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= u64::from(*byte);
+            self.state = self.state.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+impl<V, F: core::fmt::Debug> core::fmt::Debug for S3FIFOKey<V, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("S3FIFOKey")
             .field("hash", &self.hash)
             .finish()
     }
 }
 
-impl<V: Hash> Display for S3FIFOKey<V> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<V, F: LowerHex> Display for S3FIFOKey<V, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:#x}", self.hash)
     }
-}
\ No newline at end of file
+}
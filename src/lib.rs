@@ -1,13 +1,29 @@
 //! A non-thread-safe implementation of an S3-FIFO
 //! Paper here: https://jasony.me/publication/sosp23-s3fifo.pdf
+//!
+//! Without the default `std` feature, this crate only depends on `core` and `alloc`.
+//! `S3FIFOKey::new` requires `std` and is unavailable, so use `S3FIFOKey::new_seeded`
+//! or supply your own keys.
 
-use std::{
-    collections::VecDeque,
-    sync::atomic::{AtomicI8, Ordering},
-};
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use alloc::{collections::VecDeque, format, string::String, vec, vec::Vec};
+use core::cell::Cell;
+
+mod builder;
+mod cache;
+mod iter;
 mod key;
+mod view;
+mod window;
+pub use builder::S3FIFOBuilder;
+pub use cache::Cache;
+pub use iter::{IntoIter, Iter};
 pub use key::S3FIFOKey;
+pub use view::S3FIFOView;
+use window::HitWindow;
 
 /// S3FIFO is a non-thread-safe implementation of an S3-FIFO
 ///
@@ -19,6 +35,8 @@ pub use key::S3FIFOKey;
 /// 3. A ghost cache that holds keys that have been evicted from the main cache
 ///
 /// ```
+/// # #[cfg(feature = "std")]
+/// # {
 /// use s3_fifo::{S3FIFO, S3FIFOKey};
 ///
 /// // The cached value must be Clone.
@@ -36,11 +54,41 @@ pub use key::S3FIFOKey;
 ///     cache.put(key.clone(), value);
 ///     assert!(cache.get(&key).is_some());
 /// }
+/// # }
 /// ````
+///
+/// Any key that is `PartialEq + Clone` can be used directly without S3FIFOKey.
+/// Prefer this for keys that are cheap to compare, like integers or short arrays,
+/// since it skips hashing and compares the exact key. Prefer S3FIFOKey when the
+/// natural key is large or expensive to compare and a 64-bit hash of it is good enough.
+///
+/// ```
+/// use s3_fifo::S3FIFO;
+///
+/// let mut cache: S3FIFO<[u8; 4], &str> = S3FIFO::new(128);
+/// cache.put([127, 0, 0, 1], "localhost");
+/// assert_eq!(cache.get(&[127, 0, 0, 1]), Some(&"localhost"));
+/// ```
 pub struct S3FIFO<K, V> {
     small: VecDeque<Item<K, V>>,
     main: VecDeque<Item<K, V>>,
     ghost: VecDeque<Key<K>>,
+    small_capacity: usize,
+    main_capacity: usize,
+    ghost_capacity: usize,
+    promotion_threshold: i8,
+    ghost_hit_freq: i8,
+    small_freq_cap: i8,
+    main_freq_cap: i8,
+    decay_interval: usize,
+    puts_since_decay: usize,
+    max_reinsertions: usize,
+    hit_window: HitWindow,
+    next_seq: u64,
+    filtered_from_small: usize,
+    promoted_from_small: usize,
+    small_overflow: usize,
+    main_overflow: usize,
 }
 
 impl<K: PartialEq + Clone, V> S3FIFO<K, V> {
@@ -49,140 +97,721 @@ impl<K: PartialEq + Clone, V> S3FIFO<K, V> {
     /// the small cache and 90% of the capacity for the main cache.
     ///
     /// The ghost cache is also 90% of the capacity but only holds
-    /// keys and not values.
+    /// keys and not values. Use `set_ghost_capacity` to change it.
+    ///
+    /// The sizes are rounded down, so `S3FIFO::new(25)` has a small cache of 2
+    /// and a main and ghost cache of 22. Small and main are raised to at least one
+    /// item each, so `S3FIFO::new(5)` has a small cache of 1 rather than 0.
+    /// Use `with_capacities` to size them exactly.
     ///
     pub fn new(capacity: usize) -> Self {
         let small_capacity = capacity / 10;
         let main_capacity = capacity * 9 / 10;
+        Self::with_capacities(small_capacity.max(1), main_capacity.max(1), main_capacity)
+    }
+
+    ///
+    /// Create a new S3FIFO cache with exactly the given number of items
+    /// in small and main and keys in ghost.
+    ///
+    /// Ghost may be 0 to disable it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if small or main has a capacity of zero, since `put` always
+    /// admits to small and `warm` always inserts into main.
+    ///
+    pub fn with_capacities(
+        small_capacity: usize,
+        main_capacity: usize,
+        ghost_capacity: usize,
+    ) -> Self {
+        assert!(
+            small_capacity > 0,
+            "the small queue must have a nonzero capacity"
+        );
+        assert!(
+            main_capacity > 0,
+            "the main queue must have a nonzero capacity"
+        );
         S3FIFO {
             small: VecDeque::with_capacity(small_capacity),
             main: VecDeque::with_capacity(main_capacity),
-            ghost: VecDeque::with_capacity(main_capacity),
+            ghost: VecDeque::with_capacity(ghost_capacity),
+            small_capacity,
+            main_capacity,
+            ghost_capacity,
+            promotion_threshold: 1,
+            ghost_hit_freq: 1,
+            small_freq_cap: MAX_FREQ,
+            main_freq_cap: MAX_FREQ,
+            decay_interval: 0,
+            puts_since_decay: 0,
+            max_reinsertions: usize::MAX,
+            hit_window: HitWindow::new(0),
+            next_seq: 0,
+            filtered_from_small: 0,
+            promoted_from_small: 0,
+            small_overflow: 0,
+            main_overflow: 0,
         }
     }
 
+    ///
+    /// Set the frequency of an item, clamped to the frequency cap of its queue,
+    /// returning whether the key is present.
+    ///
+    pub fn set_frequency(&mut self, key: &K, freq: u8) -> bool {
+        let Some(location) = self.locate(key) else {
+            return false;
+        };
+        let cap = self.freq_cap(location.0);
+        self.item_mut(location).freq.set(freq.min(cap as u8) as i8);
+        true
+    }
+
+    ///
+    /// Pin an item so that it is never evicted, returning whether the key is present.
+    ///
+    /// Eviction skips pinned items and picks the next candidate instead. If every
+    /// candidate in a queue is pinned, nothing can be evicted and the queue grows
+    /// past its capacity rather than rejecting the new item. It stays that size until
+    /// items are removed, even after they are unpinned.
+    ///
+    pub fn pin(&mut self, key: &K) -> bool {
+        self.set_pinned(key, true)
+    }
+
+    /// Unpin an item so that it can be evicted again, returning whether the key is present.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        self.set_pinned(key, false)
+    }
+
+    fn set_pinned(&mut self, key: &K, pinned: bool) -> bool {
+        let Some(location) = self.locate(key) else {
+            return false;
+        };
+        self.item_mut(location).pinned = pinned;
+        true
+    }
+
+    ///
+    /// Set how many keys ghost remembers.
+    ///
+    /// Since ghost only holds keys, a larger ghost is cheap and recognizes more
+    /// re-requested keys, sending them straight to main. A smaller ghost makes
+    /// more re-requested keys go through small again. If ghost holds more keys
+    /// than the new capacity, the least recently evicted keys are dropped.
+    ///
+    pub fn set_ghost_capacity(&mut self, capacity: usize) {
+        self.ghost.truncate(capacity);
+        self.ghost_capacity = capacity;
+    }
+
+    ///
+    /// Set how many accesses an item in small needs before it is promoted to main.
+    ///
+    /// When an item is evicted from small, it moves to main if its frequency is greater
+    /// than the threshold and otherwise moves to ghost. The default of 1 promotes items
+    /// that were read at least twice while in small.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the threshold is not below the frequency cap of small, 3 by default,
+    /// since no item could ever be promoted.
+    ///
+    pub fn set_promotion_threshold(&mut self, threshold: i8) {
+        assert!(
+            threshold < self.small_freq_cap,
+            "a promotion threshold of {threshold} would never promote an item"
+        );
+        self.promotion_threshold = threshold;
+    }
+
+    ///
+    /// Set the lowest frequency an item starts with in main after a ghost hit,
+    /// clamped to the frequency cap of main.
+    ///
+    /// A key in ghost keeps the frequency it had when it was evicted from small, which is
+    /// often 0. Re-requesting it proves it is wanted again, yet at frequency 0 it would be
+    /// the first candidate the next time main evicts. The default of 1 lets it survive one
+    /// pass over main. An item that was evicted with a higher frequency keeps it.
+    ///
+    pub fn set_ghost_hit_freq(&mut self, freq: u8) {
+        self.ghost_hit_freq = (freq.min(i8::MAX as u8) as i8).min(self.main_freq_cap);
+    }
+
     /// Read an item from the cache.
     /// If the item is present, then its frequency is incremented and a reference is returned.
+    ///
+    /// Although this takes `&self`, the frequency is updated through interior mutability,
+    /// so reading through a shared reference still changes what is evicted next.
+    /// Use `peek` to read without any side effects.
     pub fn get(&self, key: &K) -> Option<&V> {
-        // Check item in small
-        if let Some(item) = self.small.iter().find(|item| item.key == *key) {
-            let _ = item
-                .freq
-                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
-                    if x > 2 {
-                        Some(3)
-                    } else {
-                        Some(x + 1)
-                    }
-                });
-            return Some(&item.value);
-        }
-
-        // Check item in main
-        if let Some(item) = self.main.iter().find(|item| item.key == *key) {
-            let _ = item
-                .freq
-                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
-                    if x > 2 {
-                        Some(3)
-                    } else {
-                        Some(x + 1)
-                    }
-                });
-            return Some(&item.value);
+        self.get_with_freq(key).map(|(value, _)| value)
+    }
+
+    /// Read an item from the cache without incrementing its frequency or recording a lookup.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.locate(key).map(|location| &self.item(location).value)
+    }
+
+    /// Which queue holds the key, without incrementing its frequency.
+    pub fn location(&self, key: &K) -> Option<Queue> {
+        match self.locate(key) {
+            Some((LiveQueue::Small, _)) => Some(Queue::Small),
+            Some((LiveQueue::Main, _)) => Some(Queue::Main),
+            None if self.ghost.iter().any(|k| k.key == *key) => Some(Queue::Ghost),
+            None => None,
         }
+    }
 
-        None
+    /// Whether the key is in small or main, without incrementing its frequency.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.locate(key).is_some()
+    }
+
+    ///
+    /// The sequence number stamped on an item when it was inserted, if the key is present.
+    ///
+    /// Every item inserted into small or main gets the next number, starting from 0, so
+    /// sorting by sequence recovers insertion order even after items are reinserted or
+    /// promoted. Updating an existing key keeps its number, while a key that returns
+    /// from ghost gets a new one. The counter wraps around after `u64::MAX` insertions.
+    ///
+    pub fn sequence(&self, key: &K) -> Option<u64> {
+        self.locate(key).map(|location| self.item(location).seq)
+    }
+
+    /// The number of items in small and main.
+    pub fn len(&self) -> usize {
+        self.small.len() + self.main.len()
+    }
+
+    /// Whether small and main are both empty.
+    pub fn is_empty(&self) -> bool {
+        self.small.is_empty() && self.main.is_empty()
+    }
+
+    /// The number of items that small and main hold together before evicting.
+    pub fn capacity(&self) -> usize {
+        self.small_capacity + self.main_capacity
+    }
+
+    ///
+    /// Borrow the cache through a handle that only offers queries without side effects.
+    ///
+    /// Pass the view to code that should never affect eviction, such as metrics,
+    /// since `get` increments frequencies even through a shared reference.
+    ///
+    pub fn view(&self) -> S3FIFOView<'_, K, V> {
+        S3FIFOView::new(self)
+    }
+
+    /// Read an item from the cache.
+    /// If the item is present, then its frequency is incremented and a reference is
+    /// returned along with the incremented frequency.
+    pub fn get_with_freq(&self, key: &K) -> Option<(&V, u8)> {
+        let location = self.locate(key);
+        self.hit_window.record(location.is_some());
+        let location = location?;
+        let cap = self.freq_cap(location.0);
+        let item = self.item(location);
+        let freq = item.bump(cap);
+        Some((&item.value, freq as u8))
     }
 
     /// Read an item from the cache.
     /// If the item is present, then its frequency is incremented and a mutable reference is returned.
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        // Check item in small
-        if let Some(item) = self.small.iter_mut().find(|item| item.key == *key) {
-            let _ = item
-                .freq
-                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
-                    if x > 2 {
-                        Some(3)
-                    } else {
-                        Some(x + 1)
-                    }
-                });
-            return Some(&mut item.value);
-        }
-
-        // Check item in main
-        if let Some(item) = self.main.iter_mut().find(|item| item.key == *key) {
-            let _ = item
-                .freq
-                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
-                    if x > 2 {
-                        Some(3)
-                    } else {
-                        Some(x + 1)
-                    }
-                });
-            return Some(&mut item.value);
+        let location = self.locate(key);
+        self.hit_window.record(location.is_some());
+        let location = location?;
+        let cap = self.freq_cap(location.0);
+        let item = self.item_mut(location);
+        item.bump(cap);
+        Some(&mut item.value)
+    }
+
+    /// Read many items from the cache at once.
+    /// Each hit has its frequency incremented, exactly as if `get` were called for each key.
+    /// The returned references are in the same order as `keys`.
+    ///
+    /// The cache is scanned once for all of the keys rather than once per key, stopping
+    /// early when every key has been found.
+    pub fn get_many<'a>(&'a mut self, keys: &[K]) -> Vec<Option<&'a V>> {
+        // The frequency lives in a Cell, so a shared reborrow is enough to bump it
+        // and lets every returned reference share the same lifetime.
+        let cache: &'a Self = self;
+        let mut values = vec![None; keys.len()];
+        let mut remaining = keys.len();
+        let items = (cache.small.iter().map(|item| (item, cache.small_freq_cap)))
+            .chain(cache.main.iter().map(|item| (item, cache.main_freq_cap)));
+        for (item, cap) in items {
+            if remaining == 0 {
+                break;
+            }
+            for (key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_none() && *key == item.key {
+                    item.bump(cap);
+                    *value = Some(&item.value);
+                    remaining -= 1;
+                }
+            }
         }
+        for value in &values {
+            cache.hit_window.record(value.is_some());
+        }
+        values
+    }
 
-        None
+    /// Apply `f` to an item in the cache.
+    /// If the item is present, then its frequency is incremented and true is returned.
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: &K, f: F) -> bool {
+        match self.get_mut(key) {
+            Some(value) => {
+                f(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///
+    /// Replace the value of an item if `predicate` holds for its current value.
+    ///
+    /// On success the old value is returned and the item keeps its frequency and position.
+    /// If the key is missing or the predicate fails, `new` is returned back.
+    ///
+    pub fn replace_if<F: FnOnce(&V) -> bool>(
+        &mut self,
+        key: &K,
+        new: V,
+        predicate: F,
+    ) -> Result<V, V> {
+        match self.locate(key) {
+            Some(location) if predicate(&self.item(location).value) => {
+                Ok(core::mem::replace(&mut self.item_mut(location).value, new))
+            }
+            _ => Err(new),
+        }
+    }
+
+    /// Increment the frequency of an item without reading its value.
+    /// Returns whether the item is present. This is not recorded as a lookup.
+    pub fn touch(&mut self, key: &K) -> bool {
+        let Some(location) = self.locate(key) else {
+            return false;
+        };
+        self.item(location).bump(self.freq_cap(location.0));
+        true
+    }
+
+    /// Check whether putting this key would insert it straight into main,
+    /// which is the case when the key was recently evicted and is still in ghost.
+    pub fn would_promote(&self, key: &K) -> bool {
+        self.ghost.iter().any(|k| k.key == *key)
     }
 
     /// Write an item to the cache.
     /// This may evict an item from the cache.
     /// The returnted tuple is a mutable reference to the value in the cache and any evicted value.
+    ///
+    /// A single put evicts at most one value. Making room in small either moves its oldest
+    /// item to ghost, evicting that value, or promotes it to main, which evicts at most one
+    /// value from main. Making room in main for a ghost hit evicts at most one value from main.
+    /// Keys dropped from a full ghost are not reported since they hold no values.
     pub fn put(&mut self, key: K, value: V) -> (&mut V, Option<V>) {
+        let (value, evicted, _) = self.put_with_outcome(key, value);
+        (value, evicted)
+    }
+
+    /// Write an item to the cache as with `put`, also returning where the key ended up.
+    pub fn put_with_outcome(&mut self, key: K, value: V) -> (&mut V, Option<V>, PutOutcome) {
+        if self.decay_interval > 0 {
+            self.puts_since_decay += 1;
+            if self.puts_since_decay >= self.decay_interval {
+                self.decay();
+            }
+        }
+
         // Check if the item is in the cache to maintain consistency
-        if let Some(item) = self.get_mut(&key) {
-            // Borrow checker would say that this item borrows self mutably for '1 lifetime
-            // That would mean all of the immutable borrows below would be invalid even though
-            // they are not and we are just returning here.
-            // V lives safely in this container and this referenc is now bound to the lifetime of the container in this scope.
-            let item = item as *mut V;
-            return (unsafe { &mut *item }, None);
+        if let Some(location) = self.locate(&key) {
+            let cap = self.freq_cap(location.0);
+            let item = self.item_mut(location);
+            item.bump(cap);
+            return (&mut item.value, None, PutOutcome::Updated);
         }
 
         // Check if item is in ghost to decide where to insert
-        let mut evicted = None;
-        if let Some(key) = self.ghost.iter().find(|k| k.key == key) {
-            let item = Item {
-                key: key.key.clone(),
-                value,
-                freq: key.freq.load(Ordering::Relaxed).into(),
-            };
-            if self.main.capacity() == self.main.len() {
-                evicted = self.evict_main();
-            }
+        if let Some(index) = self.ghost.iter().position(|k| k.key == key) {
+            // The key is moving to main, so it must not linger in ghost as well
+            let ghost = self.ghost.remove(index).unwrap();
+            let freq = ghost.freq.get().max(self.ghost_hit_freq);
+            let item = self.new_item(key, value, freq.min(self.main_freq_cap));
+            let evicted = self.make_room_in_main();
             self.main.push_front(item);
-            return (&mut self.main.front_mut().unwrap().value, evicted);
+            let value = &mut self.main.front_mut().unwrap().value;
+            (value, evicted, PutOutcome::PromotedFromGhost)
         } else {
-            let item = Item {
+            let item = self.new_item(key, value, 0);
+            let evicted = self.make_room_in_small();
+            self.small.push_front(item);
+            let value = &mut self.small.front_mut().unwrap().value;
+            (value, evicted, PutOutcome::AdmittedToSmall)
+        }
+    }
+
+    ///
+    /// Write many items to the cache through `put`, returning every value evicted
+    /// along the way in the order they were evicted.
+    ///
+    pub fn put_batch<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) -> Vec<V> {
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| self.put(key, value).1)
+            .collect()
+    }
+
+    ///
+    /// Read an item from the cache, or insert the value computed by `f` if it is missing.
+    ///
+    /// The returned bool is true on a hit, where the frequency is incremented as with
+    /// `get_mut`, and false on a miss, where `f` was called and its value inserted as with `put`.
+    ///
+    pub fn get_or_insert_with_status<F: FnOnce() -> V>(&mut self, key: K, f: F) -> (&mut V, bool) {
+        if let Some(location) = self.locate(&key) {
+            self.hit_window.record(true);
+            let cap = self.freq_cap(location.0);
+            let item = self.item_mut(location);
+            item.bump(cap);
+            return (&mut item.value, true);
+        }
+        self.hit_window.record(false);
+        (self.put(key, f()).0, false)
+    }
+
+    ///
+    /// Write an item to the cache only if nothing needs to be evicted to make room.
+    ///
+    /// A key in ghost needs room in main and any other key needs room in small.
+    /// If that queue is full, the key and value are returned untouched.
+    /// If the key is already in the cache, the existing value is returned as with `put`.
+    ///
+    /// This applies backpressure instead of overwriting, for callers that treat the cache
+    /// as a fixed-size staging buffer and would rather be told it is full. Note that a
+    /// ghost hit is rejected when main is full even if small has room, since the key
+    /// would have gone to main.
+    ///
+    pub fn try_put_no_evict(&mut self, key: K, value: V) -> Result<&mut V, (K, V)> {
+        if self.locate(&key).is_none() {
+            let full = if self.ghost.iter().any(|k| k.key == key) {
+                self.main.len() >= self.main_capacity
+            } else {
+                self.small.len() >= self.small_capacity
+            };
+            if full {
+                return Err((key, value));
+            }
+        }
+        Ok(self.put(key, value).0)
+    }
+
+    ///
+    /// Move an item from small to main without waiting for it to earn promotion.
+    ///
+    /// If main is full, an item is evicted from main first, just as when small
+    /// promotes an item on eviction. Returns `None` if the key is not in small,
+    /// or else the value that was evicted from main to make room, if any.
+    ///
+    pub fn promote(&mut self, key: &K) -> Option<Option<V>> {
+        let index = self.small.iter().position(|item| item.key == *key)?;
+        let item = self.small.remove(index).unwrap();
+        let evicted = self.make_room_in_main();
+        item.freq.set(item.freq.get().min(self.main_freq_cap));
+        self.main.push_front(item);
+        Some(evicted)
+    }
+
+    ///
+    /// Reserve space for at least `additional` more items, split between small and main
+    /// in proportion to their capacities.
+    ///
+    /// This is only an allocation hint. It does not change how many items each queue
+    /// holds before evicting.
+    ///
+    pub fn reserve(&mut self, additional: usize) {
+        let small = self.small_share(additional);
+        self.small.reserve(small);
+        self.main.reserve(additional - small);
+    }
+
+    /// The part of `additional` that belongs to small, computed wide enough not to overflow.
+    fn small_share(&self, additional: usize) -> usize {
+        let total = self.small_capacity as u128 + self.main_capacity as u128;
+        (additional as u128 * self.small_capacity as u128 / total) as usize
+    }
+
+    ///
+    /// Estimate the number of bytes held by the cache.
+    ///
+    /// This counts the cache itself and every slot allocated by its queues,
+    /// including slots that are reserved but not yet filled. It does not follow
+    /// pointers inside keys or values, so heap data owned by them is not included.
+    ///
+    pub fn estimated_size_bytes(&self) -> usize {
+        let item = core::mem::size_of::<Item<K, V>>();
+        let key = core::mem::size_of::<Key<K>>();
+        core::mem::size_of::<Self>()
+            + (self.small.capacity() + self.main.capacity()) * item
+            + self.ghost.capacity() * key
+    }
+
+    ///
+    /// Count the items in small and main at each frequency from 0 to 3.
+    ///
+    /// Many items at 3 suggest the hot set fits in the cache, while many items
+    /// at 0 or 1 suggest admission is churning. With frequency caps above 3,
+    /// the last bucket counts items at 3 or more.
+    ///
+    pub fn freq_histogram(&self) -> [usize; 4] {
+        let mut histogram = [0; 4];
+        for item in self.small.iter().chain(self.main.iter()) {
+            histogram[item.freq.get().clamp(0, MAX_FREQ) as usize] += 1;
+        }
+        histogram
+    }
+
+    ///
+    /// How full each queue is relative to its configured capacity.
+    ///
+    /// The capacities are the ones the cache was built with rather than the allocated
+    /// capacity of the queues. A queue with a capacity of 0 reports 0.
+    ///
+    pub fn utilization(&self) -> Utilization {
+        let ratio = |len: usize, capacity: usize| {
+            if capacity == 0 {
+                0.0
+            } else {
+                (len as f64 / capacity as f64).min(1.0)
+            }
+        };
+        Utilization {
+            small: ratio(self.small.len(), self.small_capacity),
+            main: ratio(self.main.len(), self.main_capacity),
+            ghost: ratio(self.ghost.len(), self.ghost_capacity),
+        }
+    }
+
+    ///
+    /// Whether main is more than 95% full.
+    ///
+    /// Once main is full, every promotion from small evicts from main, so a cache that
+    /// stays under pressure may be too small for its working set.
+    ///
+    pub fn is_under_pressure(&self) -> bool {
+        self.utilization().main > 0.95
+    }
+
+    ///
+    /// The fraction of recent lookups that found their key.
+    ///
+    /// Only lookups are recorded: `get`, `get_with_freq`, `get_mut`, `get_many`, `update`,
+    /// `get_or_insert_with_status`, `Cache::get`, and `contains` for sets. Writes such as
+    /// `put` and queries without side effects such as `peek` are not, and neither is
+    /// `touch`, which signals importance rather than looking anything up.
+    ///
+    /// The window size is set with `S3FIFOBuilder::hit_rate_window` and nothing is
+    /// recorded without it. `clear` empties the window. Returns 0 if no lookups are
+    /// recorded yet.
+    ///
+    pub fn recent_hit_rate(&self) -> f64 {
+        self.hit_window.hit_rate()
+    }
+
+    ///
+    /// The fraction of items evicted from small that went to ghost instead of main.
+    ///
+    /// A high ratio means small is filtering out items that are read once, such as
+    /// those from a scan, before they can displace the working set in main. Items moved
+    /// by `promote` or removed directly are not counted. The counts are reset by `clear`.
+    /// Returns 0 if nothing has been evicted from small since the cache was created or
+    /// last cleared.
+    ///
+    pub fn scan_filter_ratio(&self) -> f64 {
+        let total = self.filtered_from_small + self.promoted_from_small;
+        if total == 0 {
+            return 0.0;
+        }
+        self.filtered_from_small as f64 / total as f64
+    }
+
+    ///
+    /// Halve the frequency of every item in small and main.
+    ///
+    /// Without decay, an item that was hot long ago keeps its frequency until
+    /// `evict_main` passes over it enough times to wear it down one step at a time.
+    /// Decaying ages every item at once, so items that went cold reach zero and are
+    /// evicted on the first pass rather than being reinserted repeatedly.
+    ///
+    /// Use `S3FIFOBuilder::decay_interval` to decay automatically every N calls to `put`.
+    ///
+    pub fn decay(&mut self) {
+        self.puts_since_decay = 0;
+        for item in self.small.iter_mut().chain(self.main.iter_mut()) {
+            item.freq.set(item.freq.get() / 2);
+        }
+    }
+
+    ///
+    /// Run periodic upkeep, such as sweeping expired entries, meant to be called on a timer.
+    ///
+    /// This is currently a no-op, since entries have no expiry time or weight to reclaim.
+    /// Eviction already happens inline in `put`, so nothing is deferred to this call.
+    ///
+    pub fn maintain(&mut self) {}
+
+    ///
+    /// Insert entries directly into main with a frequency of 1.
+    ///
+    /// This intentionally bypasses the admission filter of small, which is useful
+    /// when loading a known hot set on startup. Entries already in the cache are kept,
+    /// and items evicted from main to make room are dropped.
+    ///
+    pub fn warm<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            if self.locate(&key).is_some() {
+                continue;
+            }
+            self.put_into(key, value, LiveQueue::Main);
+            self.main[0].freq.set(1.min(self.main_freq_cap));
+        }
+    }
+
+    ///
+    /// Insert an item at the front of the given queue with a frequency of 0,
+    /// returning the previous value of the key and the value evicted to make room.
+    ///
+    /// This skips the ghost check and the admission filter, which makes it possible
+    /// to reconstruct an exact state when replaying a trace or loading a snapshot.
+    /// Any existing item for the key is removed first, and the key is removed from
+    /// ghost. It can create states that the rest of the API never would, such as an
+    /// item in main that was never read.
+    ///
+    pub fn put_into(&mut self, key: K, value: V, queue: LiveQueue) -> (Option<V>, Option<V>) {
+        let replaced = self.remove(&key);
+        if let Some(index) = self.ghost.iter().position(|k| k.key == key) {
+            self.ghost.remove(index);
+        }
+        let evicted = match queue {
+            LiveQueue::Small => self.make_room_in_small(),
+            LiveQueue::Main => self.make_room_in_main(),
+        };
+        let item = self.new_item(key, value, 0);
+        match queue {
+            LiveQueue::Small => self.small.push_front(item),
+            LiveQueue::Main => self.main.push_front(item),
+        }
+        (replaced, evicted)
+    }
+
+    ///
+    /// Insert the items of another cache into this one through `put`, returning the
+    /// values that did not survive.
+    ///
+    /// Items keep their frequency and pin, so items that were hot in `other` are
+    /// promoted out of small as usual. When a key is in both caches, the item with the
+    /// higher frequency is kept, preferring this cache on a tie, and the other value is
+    /// returned along with any values evicted to make room. The ghost of `other` is
+    /// discarded.
+    ///
+    pub fn merge(&mut self, other: S3FIFO<K, V>) -> Vec<V> {
+        let mut displaced = Vec::new();
+        // Oldest first, so the most recent items of other are also the most recent here
+        let items = other
+            .main
+            .into_iter()
+            .rev()
+            .chain(other.small.into_iter().rev());
+        for other_item in items {
+            if let Some(location) = self.locate(&other_item.key) {
+                let cap = self.freq_cap(location.0);
+                let item = self.item_mut(location);
+                item.pinned |= other_item.pinned;
+                if other_item.freq.get() > item.freq.get() {
+                    item.freq.set(other_item.freq.get().min(cap));
+                    displaced.push(core::mem::replace(&mut item.value, other_item.value));
+                } else {
+                    displaced.push(other_item.value);
+                }
+                continue;
+            }
+            let Item {
                 key,
                 value,
-                freq: 0.into(),
-            };
-            if self.small.capacity() == self.small.len() {
-                evicted = self.evict_small();
+                freq,
+                pinned,
+                ..
+            } = other_item;
+            displaced.extend(self.put(key.clone(), value).1);
+            if let Some(location) = self.locate(&key) {
+                let cap = self.freq_cap(location.0);
+                let item = self.item_mut(location);
+                item.freq.set(item.freq.get().max(freq.get().min(cap)));
+                item.pinned = pinned;
             }
-            self.small.push_front(item);
-            return (&mut self.small.front_mut().unwrap().value, evicted);
         }
+        displaced
     }
 
     /// Remove an item from the cache.
     pub fn pop(&mut self) -> Option<V> {
         // Popping from small may move an item to main
         while !self.small.is_empty() {
+            let len = self.small.len();
             if let Some(value) = self.evict_small() {
                 return Some(value);
             }
+            // Every item left in small is pinned
+            if self.small.len() == len {
+                break;
+            }
         }
 
         // Try evicting from main to keep ghost up to date
         self.evict_main()
     }
 
+    ///
+    /// Remove an item from the cache and return its value.
+    ///
+    /// The key is not added to ghost since it was not evicted.
+    ///
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let item = match self.locate(key)? {
+            (LiveQueue::Small, index) => self.small.remove(index),
+            (LiveQueue::Main, index) => self.main.remove(index),
+        };
+        item.map(|item| item.value)
+    }
+
+    ///
+    /// Remove all items and ghost keys from the cache, keeping the capacities.
+    ///
+    /// The statistics behind `scan_filter_ratio` and `recent_hit_rate` are reset too,
+    /// so they describe the cache as it is refilled rather than what it held before.
+    ///
+    pub fn clear(&mut self) {
+        self.small.clear();
+        self.main.clear();
+        self.ghost.clear();
+        self.small_overflow = 0;
+        self.main_overflow = 0;
+        self.filtered_from_small = 0;
+        self.promoted_from_small = 0;
+        self.hit_window.clear();
+    }
+
     /// Remove all items from the cache, leaving it empty and with the same capacity.
     pub fn drain(&mut self) -> Vec<V> {
         self.ghost.clear();
@@ -192,64 +821,419 @@ impl<K: PartialEq + Clone, V> S3FIFO<K, V> {
         values
     }
 
-    fn evict_small(&mut self) -> Option<V> {
-        if self.small.is_empty() {
-            return None;
-        }
-        let item = self.small.pop_back().unwrap();
-        let freq = item.freq.load(Ordering::Relaxed);
-        if freq > 1 {
-            let mut value = None;
-            if self.main.capacity() == self.main.len() {
-                value = self.evict_main();
-            }
-            self.main.push_front(item);
-            value
-        } else {
-            let Item { key, value, freq } = item;
-            if self.ghost.capacity() == self.ghost.len() {
-                self.ghost.pop_back();
+    ///
+    /// Remove all items from small and return their values, keeping main and ghost as they are.
+    ///
+    /// This clears out items that have not proven themselves yet without losing the
+    /// working set in main. The keys are not added to ghost since they were not evicted.
+    ///
+    pub fn drain_small(&mut self) -> Vec<V> {
+        self.small.drain(..).map(|item| item.value).collect()
+    }
+
+    /// Remove all items from main and return their values, keeping small and ghost as they are.
+    pub fn drain_main(&mut self) -> Vec<V> {
+        self.main.drain(..).map(|item| item.value).collect()
+    }
+
+    ///
+    /// Remove every item for which `f` returns true and return the removed entries.
+    ///
+    /// The remaining items keep their order. Removed keys are not added to ghost
+    /// since they were not evicted.
+    ///
+    pub fn drain_filter<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Vec<(K, V)> {
+        let mut removed = Vec::new();
+        for queue in [&mut self.small, &mut self.main] {
+            // Rotate through the queue once so that survivors end up in their original order
+            for _ in 0..queue.len() {
+                let item = queue.pop_front().unwrap();
+                if f(&item.key, &item.value) {
+                    removed.push((item.key, item.value));
+                } else {
+                    queue.push_back(item);
+                }
             }
-            self.ghost.push_front(Key { key, freq });
-            Some(value)
         }
+        removed
     }
 
-    fn evict_main(&mut self) -> Option<V> {
-        // The maximum freq is 3, so if the main cache is full and all items have freq 3,
-        // then the maximum number of iterations is 3 * main.len() + 1
-        let mut iters = (3 * self.main.len() + 1) as isize;
-        while iters > 0 {
-            let Some(item) = self.main.pop_back() else {
-                return None;
-            };
-            iters -= 1;
-            let freq = item.freq.load(Ordering::Relaxed);
-            if freq > 0 {
-                item.freq.fetch_sub(1, Ordering::Relaxed);
-                self.main.push_front(item);
+    /// Check the internal invariants of the cache.
+    ///
+    /// This is intended for debugging and tests, as it compares every pair of keys.
+    /// Returns a description of the first violation found:
+    /// - a key is present more than once in small and main
+    /// - a live key is also present in ghost
+    /// - a frequency is negative or above the frequency cap of its queue
+    /// - a queue holds more entries than its capacity, beyond the items it had to
+    ///   keep because every eviction candidate was pinned
+    pub fn validate(&self) -> Result<(), String> {
+        let queues = [
+            (
+                "small",
+                self.small.len(),
+                self.small_capacity,
+                self.small_overflow,
+            ),
+            (
+                "main",
+                self.main.len(),
+                self.main_capacity,
+                self.main_overflow,
+            ),
+            ("ghost", self.ghost.len(), self.ghost_capacity, 0),
+        ];
+        for (name, len, capacity, overflow) in queues {
+            if len > capacity + overflow {
+                return Err(format!(
+                    "{name} holds {len} entries but its capacity is {capacity}"
+                ));
+            }
+        }
+
+        let live: Vec<(&str, usize, &Item<K, V>)> = self
+            .small
+            .iter()
+            .enumerate()
+            .map(|(i, item)| ("small", i, item))
+            .chain(
+                self.main
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| ("main", i, item)),
+            )
+            .collect();
+        for (n, (name, i, item)) in live.iter().enumerate() {
+            let cap = if *name == "small" {
+                self.small_freq_cap
             } else {
-                return Some(item.value);
+                self.main_freq_cap
+            };
+            if !(0..=cap).contains(&item.freq.get()) {
+                return Err(format!("{name}[{i}] has frequency {}", item.freq.get()));
+            }
+            if let Some((other, j, _)) = live[n + 1..].iter().find(|(_, _, o)| o.key == item.key) {
+                return Err(format!("{name}[{i}] has the same key as {other}[{j}]"));
+            }
+            if let Some(j) = self.ghost.iter().position(|k| k.key == item.key) {
+                return Err(format!("{name}[{i}] has the same key as ghost[{j}]"));
             }
         }
-        None
-    }
-}
 
-struct Item<K, V> {
-    key: K,
-    value: V,
-    freq: AtomicI8, // not thread-safe
-}
+        for (i, key) in self.ghost.iter().enumerate() {
+            if !(0..=self.small_freq_cap).contains(&key.freq.get()) {
+                return Err(format!("ghost[{i}] has frequency {}", key.freq.get()));
+            }
+        }
 
-struct Key<K> {
+        Ok(())
+    }
+
+    /// Find the queue and index of an item, visiting each item at most once.
+    fn locate(&self, key: &K) -> Option<(LiveQueue, usize)> {
+        if let Some(index) = self.small.iter().position(|item| item.key == *key) {
+            return Some((LiveQueue::Small, index));
+        }
+        self.main
+            .iter()
+            .position(|item| item.key == *key)
+            .map(|index| (LiveQueue::Main, index))
+    }
+
+    /// The highest frequency of an item in the queue.
+    fn freq_cap(&self, queue: LiveQueue) -> i8 {
+        match queue {
+            LiveQueue::Small => self.small_freq_cap,
+            LiveQueue::Main => self.main_freq_cap,
+        }
+    }
+
+    fn new_item(&mut self, key: K, value: V, freq: i8) -> Item<K, V> {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Item {
+            key,
+            value,
+            freq: freq.into(),
+            pinned: false,
+            seq,
+        }
+    }
+
+    fn item(&self, (queue, index): (LiveQueue, usize)) -> &Item<K, V> {
+        match queue {
+            LiveQueue::Small => &self.small[index],
+            LiveQueue::Main => &self.main[index],
+        }
+    }
+
+    fn item_mut(&mut self, (queue, index): (LiveQueue, usize)) -> &mut Item<K, V> {
+        match queue {
+            LiveQueue::Small => &mut self.small[index],
+            LiveQueue::Main => &mut self.main[index],
+        }
+    }
+
+    ///
+    /// Evict from small if it is full, before an item is pushed onto it.
+    ///
+    /// If every candidate is pinned, nothing is evicted and small is allowed to hold
+    /// one more item past its capacity. Removing items takes that allowance back.
+    ///
+    fn make_room_in_small(&mut self) -> Option<V> {
+        let len = self.small.len();
+        let excess = len.saturating_sub(self.small_capacity);
+        self.small_overflow = self.small_overflow.min(excess);
+        if len < self.small_capacity {
+            return None;
+        }
+        let evicted = self.evict_small();
+        if self.small.len() == len {
+            self.small_overflow += 1;
+        }
+        evicted
+    }
+
+    /// Evict from main if it is full, before an item is pushed onto it, as with small.
+    fn make_room_in_main(&mut self) -> Option<V> {
+        let len = self.main.len();
+        let excess = len.saturating_sub(self.main_capacity);
+        self.main_overflow = self.main_overflow.min(excess);
+        if len < self.main_capacity {
+            return None;
+        }
+        let evicted = self.evict_main();
+        if self.main.len() == len {
+            self.main_overflow += 1;
+        }
+        evicted
+    }
+
+    fn evict_small(&mut self) -> Option<V> {
+        // Pinned items are rotated to the front, so one pass visits every candidate
+        for _ in 0..self.small.len() {
+            let item = self.small.pop_back().unwrap();
+            let freq = item.freq.get();
+            if freq > self.promotion_threshold {
+                let value = self.make_room_in_main();
+                item.freq.set(freq.min(self.main_freq_cap));
+                self.main.push_front(item);
+                self.promoted_from_small += 1;
+                return value;
+            } else if item.pinned {
+                self.small.push_front(item);
+            } else {
+                let Item {
+                    key, value, freq, ..
+                } = item;
+                self.push_ghost(Key { key, freq });
+                self.filtered_from_small += 1;
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn push_ghost(&mut self, key: Key<K>) {
+        if self.ghost_capacity == 0 {
+            return;
+        }
+        // A key is only remembered once, with the highest frequency it was seen with
+        if let Some(index) = self.ghost.iter().position(|k| k.key == key.key) {
+            let stale = self.ghost.remove(index).unwrap();
+            key.freq.set(key.freq.get().max(stale.freq.get()));
+        } else if self.ghost.len() >= self.ghost_capacity {
+            self.ghost.pop_back();
+        }
+        self.ghost.push_front(key);
+    }
+
+    fn evict_main(&mut self) -> Option<V> {
+        // After as many passes over main as its frequency cap, every unpinned item
+        // has freq 0, and the next pass evicts the first unpinned item it reaches
+        let mut iters = (self.main_freq_cap as usize + 1) * self.main.len();
+        let mut reinsertions = 0;
+        while iters > 0 {
+            let item = self.main.pop_back()?;
+            iters -= 1;
+            let freq = item.freq.get();
+            if item.pinned {
+                self.main.push_front(item);
+            } else if freq > 0 && reinsertions < self.max_reinsertions {
+                item.freq.set(freq - 1);
+                self.main.push_front(item);
+                reinsertions += 1;
+            } else {
+                return Some(item.value);
+            }
+        }
+
+        // Only frequencies above the cap can exhaust the bound, so evict the oldest unpinned item
+        let index = self.main.iter().rposition(|item| !item.pinned)?;
+        self.main.remove(index).map(|item| item.value)
+    }
+}
+
+///
+/// With a value of `()`, the cache acts as a scan-resistant set of keys.
+///
+/// ```
+/// use s3_fifo::S3FIFO;
+///
+/// let mut seen: S3FIFO<u64, ()> = S3FIFO::new(1000);
+/// assert!(seen.insert(42));
+/// assert!(!seen.insert(42));
+/// assert!(seen.contains(&42));
+/// assert!(seen.remove(&42).is_some());
+/// assert!(!seen.contains(&42));
+/// ```
+///
+impl<K: PartialEq + Clone> S3FIFO<K, ()> {
+    /// Add a key to the set, returning whether it was newly inserted.
+    /// This may evict another key from the set.
+    pub fn insert(&mut self, key: K) -> bool {
+        let inserted = self.locate(&key).is_none();
+        self.put(key, ());
+        inserted
+    }
+
+    /// Check whether a key is in the set.
+    /// As with `get`, a present key has its frequency incremented and the lookup is recorded.
+    pub fn contains(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+}
+
+/// Two caches are equal if small, main, and ghost hold the same entries with
+/// the same frequencies in the same order, since order determines what is evicted next.
+/// The queue capacities, other settings, and sequence numbers are not compared.
+impl<K: PartialEq, V: PartialEq> PartialEq for S3FIFO<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.small == other.small && self.main == other.main && self.ghost == other.ghost
+    }
+}
+
+impl<K: core::fmt::Debug, V: core::fmt::Debug> core::fmt::Debug for S3FIFO<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("S3FIFO")
+            .field("small", &self.small)
+            .field("main", &self.main)
+            .field("ghost", &self.ghost)
+            .finish()
+    }
+}
+
+/// Where `put_with_outcome` placed a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PutOutcome {
+    /// The key was already in the cache, so its frequency was incremented
+    /// and the existing value was kept
+    Updated,
+    /// The key was new, so it was admitted to small
+    AdmittedToSmall,
+    /// The key was in ghost, so it was inserted straight into main
+    PromotedFromGhost,
+}
+
+///
+/// The fraction of each queue's capacity in use, from 0 to 1.
+///
+/// Queues with pinned items can grow past their capacity, which still reports 1.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utilization {
+    /// Items in small over its capacity
+    pub small: f64,
+    /// Items in main over its capacity
+    pub main: f64,
+    /// Keys in ghost over its capacity
+    pub ghost: f64,
+}
+
+/// The queues of an S3FIFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Queue {
+    /// The queue that new keys are admitted to
+    Small,
+    /// The queue of items that were read again or recently re-requested
+    Main,
+    /// The queue of keys recently evicted from small, without their values
+    Ghost,
+}
+
+/// The queues that hold items rather than only keys, which `put_into` can insert into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiveQueue {
+    /// The queue that new keys are admitted to
+    Small,
+    /// The queue of items that were read again or recently re-requested
+    Main,
+}
+
+/// The default saturating upper bound of an entry's frequency
+const MAX_FREQ: i8 = 3;
+
+struct Item<K, V> {
     key: K,
-    freq: AtomicI8, // not thread-safe
+    value: V,
+    freq: Cell<i8>, // not thread-safe
+    pinned: bool,
+    seq: u64,
+}
+
+/// The sequence number is bookkeeping and does not affect eviction, so it is not compared
+impl<K: PartialEq, V: PartialEq> PartialEq for Item<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+            && self.value == other.value
+            && self.freq == other.freq
+            && self.pinned == other.pinned
+    }
+}
+
+impl<K, V> Item<K, V> {
+    /// Increment the frequency, saturating at `cap`, and return the new frequency
+    fn bump(&self, cap: i8) -> i8 {
+        let freq = self.freq.get();
+        let freq = if freq >= cap { cap } else { freq + 1 };
+        self.freq.set(freq);
+        freq
+    }
+}
+
+#[derive(PartialEq)]
+struct Key<K> {
+    key: K,
+    freq: Cell<i8>, // not thread-safe
+}
+
+impl<K: core::fmt::Debug, V: core::fmt::Debug> core::fmt::Debug for Item<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Item")
+            .field("key", &self.key)
+            .field("value", &self.value)
+            .field("freq", &self.freq.get())
+            .field("pinned", &self.pinned)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+impl<K: core::fmt::Debug> core::fmt::Debug for Key<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Key")
+            .field("key", &self.key)
+            .field("freq", &self.freq.get())
+            .finish()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
+
     use super::*;
+    use std::prelude::rust_2021::*;
     use std::{
         collections::hash_map::DefaultHasher,
         hash::{Hash, Hasher},
@@ -276,6 +1260,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn can_fill_cache() {
         // Create a cache with a capacity of 10
         let mut cache = S3FIFO::new(10);
@@ -302,18 +1287,919 @@ mod tests {
         cache.put(repeat_key, repeat_value);
 
         assert_eq!(cache.small.len(), 1);
-        assert_eq!(cache.ghost.len(), 9);
+        assert_eq!(cache.ghost.len(), 8);
         assert_eq!(cache.main.len(), 1);
 
         // Increment main
         let repeat_value = Abc { a: 0, b: 0, c: 0 };
         let repeat_key = S3FIFOKey::new(&repeat_value);
         assert!(cache.get(&repeat_key).is_some());
-        // Do not insert again or else duplicate keys will be in the cache
-        // cache.put(repeat_key, repeat_value);
+        // Inserting again keeps the existing entry
+        cache.put(repeat_key, repeat_value);
 
         assert_eq!(cache.small.len(), 1);
-        assert_eq!(cache.ghost.len(), 9);
+        assert_eq!(cache.ghost.len(), 8);
         assert_eq!(cache.main.len(), 1);
     }
+
+    #[test]
+    fn repeated_put_does_not_duplicate() {
+        let mut cache = S3FIFO::new(10);
+        for i in 0..10 {
+            cache.put(i, i);
+        }
+
+        for _ in 0..5 {
+            cache.put(0, 0);
+            let count = cache.small.iter().filter(|item| item.key == 0).count()
+                + cache.main.iter().filter(|item| item.key == 0).count()
+                + cache.ghost.iter().filter(|key| key.key == 0).count();
+            assert_eq!(count, 1);
+            assert_eq!(cache.location(&0), Some(Queue::Main));
+            assert_eq!(cache.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn can_get_many() {
+        let mut cache = S3FIFO::new(100);
+        cache.put(1, "one");
+        cache.put(2, "two");
+
+        let values = cache.get_many(&[2, 3, 1]);
+        assert_eq!(values, vec![Some(&"two"), None, Some(&"one")]);
+        assert_eq!(cache.small[0].freq.get(), 1);
+        assert_eq!(cache.small[1].freq.get(), 1);
+
+        // Each requested hit is counted, including repeats, and misses change nothing
+        let values = cache.get_many(&[1, 4, 1, 4]);
+        assert_eq!(values, vec![Some(&"one"), None, Some(&"one"), None]);
+        assert_eq!(cache.small[1].freq.get(), 3);
+        assert_eq!(cache.small[0].freq.get(), 1);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn can_validate() {
+        let mut cache = S3FIFO::new(10);
+        for i in 0..100 {
+            cache.put(i % 13, i);
+            cache.get(&(i % 7));
+            assert_eq!(cache.validate(), Ok(()));
+        }
+
+        cache.main.push_front(Item {
+            key: 3,
+            value: 0,
+            freq: 4.into(),
+            pinned: false,
+            seq: 0,
+        });
+        assert!(cache.validate().is_err());
+    }
+
+    #[test]
+    fn can_iterate() {
+        let mut cache = S3FIFO::new(100);
+        for i in 0..5 {
+            cache.put(i, i * 10);
+        }
+
+        let mut borrowed: Vec<(u32, u32)> = cache.iter().map(|(k, v)| (*k, *v)).collect();
+        let mut owned: Vec<(u32, u32)> = cache.into_iter().collect();
+        borrowed.sort();
+        owned.sort();
+        assert_eq!(borrowed, owned);
+        assert_eq!(owned, vec![(0, 0), (1, 10), (2, 20), (3, 30), (4, 40)]);
+    }
+
+    #[test]
+    fn can_build_cache() {
+        let cache: S3FIFO<u32, u32> = S3FIFOBuilder::new(100).ghost(1000).build();
+        assert_eq!(cache.small_capacity, 10);
+        assert_eq!(cache.main_capacity, 90);
+        assert_eq!(cache.ghost_capacity, 1000);
+
+        let cache: S3FIFO<u32, u32> = S3FIFOBuilder::new(0).small(1).main(2).build();
+        assert_eq!(cache.small_capacity, 1);
+        assert_eq!(cache.main_capacity, 2);
+        assert_eq!(cache.ghost_capacity, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cannot_build_empty_small() {
+        let _: S3FIFO<u32, u32> = S3FIFOBuilder::new(5).build();
+    }
+
+    #[test]
+    fn can_raise_promotion_threshold() {
+        let mut cache = S3FIFO::new(10);
+        cache.set_promotion_threshold(2);
+
+        // Two reads are no longer enough to be promoted
+        cache.put(0, 0);
+        cache.get(&0);
+        cache.get(&0);
+        cache.put(1, 1);
+        assert!(cache.main.is_empty());
+        assert_eq!(cache.ghost.len(), 1);
+
+        // But three reads are
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.put(2, 2);
+        assert_eq!(cache.main.len(), 1);
+    }
+
+    #[test]
+    fn can_decay() {
+        let mut cache = S3FIFOBuilder::new(100).decay_interval(3).build();
+        cache.put(0, 0);
+        for _ in 0..4 {
+            cache.get(&0);
+        }
+        assert_eq!(cache.small[0].freq.get(), 3);
+
+        cache.decay();
+        assert_eq!(cache.small[0].freq.get(), 1);
+
+        // The third put triggers an automatic decay
+        cache.get(&0);
+        cache.put(1, 1);
+        cache.put(2, 2);
+        assert_eq!(cache.small[2].freq.get(), 2);
+        cache.put(3, 3);
+        assert_eq!(cache.small[3].freq.get(), 1);
+    }
+
+    #[test]
+    fn view_does_not_change_frequencies() {
+        let mut cache = S3FIFO::new(100);
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.put(3, 3);
+        for i in 4..14 {
+            cache.put(i, i);
+        }
+
+        let view = cache.view();
+        assert_eq!(view.len(), 10);
+        assert!(!view.is_empty());
+        assert_eq!(view.peek(&13), Some(&13));
+        assert!(view.contains_key(&13));
+        assert!(!view.contains_key(&1));
+        assert_eq!(view.ghost_keys().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(view.iter().count(), 10);
+        assert_eq!(view.freq_histogram(), [10, 0, 0, 0]);
+        assert!(cache.small.iter().all(|item| item.freq.get() == 0));
+    }
+
+    #[test]
+    fn ghost_hit_survives_next_eviction() {
+        for (ghost_hit_freq, survives) in [(1, true), (0, false)] {
+            let mut cache = S3FIFO::with_capacities(1, 2, 2);
+            cache.set_ghost_hit_freq(ghost_hit_freq);
+            cache.put(0, 0);
+            cache.put(1, 1);
+            assert_eq!(
+                cache.put_with_outcome(0, 0).2,
+                PutOutcome::PromotedFromGhost
+            );
+
+            // A newer item in main that has not been read since
+            cache.warm([(5, 5)]);
+            cache.set_frequency(&5, 0);
+
+            cache.put(2, 2);
+            let (_, evicted) = cache.put(1, 1);
+            assert_eq!(cache.contains_key(&0), survives);
+            assert_eq!(evicted, Some(if survives { 5 } else { 0 }));
+        }
+    }
+
+    #[test]
+    fn can_merge_caches() {
+        let mut a = S3FIFO::new(100);
+        let mut b = S3FIFO::new(100);
+        for i in 1..=5 {
+            a.put(i, i);
+        }
+        for i in 4..=8 {
+            b.put(i, i * 10);
+        }
+        a.set_frequency(&5, 3);
+        b.set_frequency(&4, 2);
+        b.set_frequency(&8, 3);
+
+        // Key 4 is hotter in b and key 5 is hotter in a
+        assert_eq!(a.merge(b), vec![4, 50]);
+        assert_eq!(a.len(), 8);
+        assert_eq!(a.get_with_freq(&4), Some((&40, 3)));
+        assert_eq!(a.peek(&5), Some(&5));
+        assert_eq!(a.peek(&6), Some(&60));
+        assert_eq!(a.freq_histogram(), [5, 0, 0, 3]);
+
+        // Items that were hot in b are promoted out of small
+        for i in 9..20 {
+            a.put(i, i);
+        }
+        assert_eq!(a.location(&8), Some(Queue::Main));
+        assert!(a.validate().is_ok());
+    }
+
+    #[test]
+    fn can_put_into_queue() {
+        let mut cache = S3FIFO::with_capacities(2, 2, 2);
+        assert_eq!(cache.put_into(0, 0, LiveQueue::Main), (None, None));
+        assert_eq!(cache.location(&0), Some(Queue::Main));
+        assert_eq!(cache.main[0].freq.get(), 0);
+
+        // A key in ghost is not sent to main
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.put(3, 3);
+        assert_eq!(cache.ghost_keys().collect::<Vec<_>>(), vec![&1]);
+        cache.put_into(1, 10, LiveQueue::Small);
+        assert_eq!(cache.location(&1), Some(Queue::Small));
+        assert_eq!(cache.ghost_keys().collect::<Vec<_>>(), vec![&2]);
+
+        // An existing item moves to the requested queue and its old value is returned
+        assert_eq!(cache.put_into(3, 30, LiveQueue::Main), (Some(3), None));
+        assert_eq!(cache.location(&3), Some(Queue::Main));
+        assert_eq!(cache.peek(&3), Some(&30));
+        assert_eq!(cache.put_into(4, 4, LiveQueue::Main), (None, Some(0)));
+
+        assert_eq!(cache.location(&0), None);
+        assert!(cache.validate().is_ok());
+    }
+
+    #[test]
+    fn put_returns_stored_value() {
+        let mut cache = S3FIFO::new(100);
+        *cache.put(1, 1).0 = 10;
+        assert_eq!(cache.peek(&1), Some(&10));
+
+        // Updating an existing key returns the value already in the cache
+        *cache.put(1, 2).0 += 1;
+        assert_eq!(cache.peek(&1), Some(&11));
+    }
+
+    #[test]
+    fn items_keep_insertion_sequence() {
+        let mut cache = S3FIFO::new(100);
+        for i in 0..12 {
+            cache.put(i, i);
+            if i == 1 {
+                cache.get(&1);
+                cache.get(&1);
+            }
+        }
+        // Key 1 was promoted to main and key 0 was evicted to ghost
+        assert_eq!(cache.location(&1), Some(Queue::Main));
+        assert_eq!(cache.location(&0), Some(Queue::Ghost));
+        assert_eq!(cache.sequence(&1), Some(1));
+        assert_eq!(cache.sequence(&11), Some(11));
+        assert_eq!(cache.sequence(&0), None);
+
+        // Updating keeps the number and returning from ghost gets a new one
+        cache.put(11, 11);
+        assert_eq!(cache.sequence(&11), Some(11));
+        cache.put(0, 0);
+        assert_eq!(cache.sequence(&0), Some(12));
+    }
+
+    #[test]
+    fn can_use_cache_trait() {
+        fn exercise<C: Cache<u32, u32>>(cache: &mut C) {
+            assert_eq!(cache.capacity(), 100);
+            for i in 0..10 {
+                assert_eq!(cache.put(i, i), None);
+            }
+            assert_eq!(cache.put(10, 10), Some(0));
+            assert_eq!(cache.get(&10), Some(&10));
+            assert_eq!(cache.remove(&10), Some(10));
+            assert_eq!(cache.len(), 9);
+            cache.clear();
+            assert!(cache.is_empty());
+        }
+
+        let mut cache = S3FIFO::new(100);
+        exercise(&mut cache);
+        assert!(cache.ghost.is_empty());
+    }
+
+    #[test]
+    fn can_measure_utilization() {
+        let mut cache = S3FIFO::with_capacities(4, 20, 0);
+        assert_eq!(
+            cache.utilization(),
+            Utilization {
+                small: 0.0,
+                main: 0.0,
+                ghost: 0.0
+            }
+        );
+
+        cache.put(0, 0);
+        cache.warm((1..11).map(|i| (i, i)));
+        assert_eq!(cache.utilization().small, 0.25);
+        assert_eq!(cache.utilization().main, 0.5);
+        assert!(!cache.is_under_pressure());
+
+        cache.warm((11..30).map(|i| (i, i)));
+        assert_eq!(cache.utilization().main, 1.0);
+        assert!(cache.is_under_pressure());
+    }
+
+    #[test]
+    #[should_panic]
+    fn cannot_build_small_freq_cap_at_threshold() {
+        let _: S3FIFO<u32, u32> = S3FIFOBuilder::new(100).small_freq_cap(1).build();
+    }
+
+    #[test]
+    fn ghost_hit_freq_is_clamped_to_main_cap() {
+        let mut cache: S3FIFO<u32, u32> = S3FIFOBuilder::new(100).main_freq_cap(2).build();
+        cache.set_ghost_hit_freq(200);
+        assert_eq!(cache.ghost_hit_freq, 2);
+    }
+
+    #[test]
+    fn can_cap_frequencies_per_queue() {
+        let mut cache: S3FIFO<u32, u32> = S3FIFOBuilder::new(100)
+            .small_freq_cap(5)
+            .main_freq_cap(1)
+            .build();
+        cache.put(0, 0);
+        for _ in 0..6 {
+            cache.get(&0);
+        }
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 5)));
+
+        // Promotion clamps the frequency to the cap of main
+        for i in 1..11 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.location(&0), Some(Queue::Main));
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 1)));
+        assert_eq!(cache.freq_histogram(), [10, 1, 0, 0]);
+        assert!(cache.validate().is_ok());
+
+        // As does promoting directly
+        for _ in 0..5 {
+            cache.get(&10);
+        }
+        assert_eq!(cache.promote(&10), Some(None));
+        assert_eq!(cache.peek(&10), Some(&10));
+        assert_eq!(cache.main[0].freq.get(), 1);
+        assert!(cache.validate().is_ok());
+    }
+
+    #[test]
+    fn can_put_batch() {
+        let mut cache = S3FIFO::new(100);
+        cache.warm((0..90).map(|i| (i, i)));
+        cache.put(90, 90);
+        cache.get(&90);
+        cache.get(&90);
+
+        // Promoting 90 out of small evicts from main as well
+        let evicted = cache.put_batch((91..102).map(|i| (i, i)));
+        assert_eq!(evicted, vec![0, 91]);
+        assert_eq!(cache.location(&90), Some(Queue::Main));
+    }
+
+    #[test]
+    fn can_measure_scan_filtering() {
+        let mut cache = S3FIFO::new(100);
+        assert_eq!(cache.scan_filter_ratio(), 0.0);
+
+        // A hot key read between the items of a scan
+        cache.put(0, 0);
+        cache.get(&0);
+        cache.get(&0);
+        for i in 1..20 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.location(&0), Some(Queue::Main));
+        assert_eq!(cache.scan_filter_ratio(), 0.9);
+    }
+
+    #[test]
+    fn maintain_leaves_items_in_place() {
+        let mut cache = S3FIFO::new(100);
+        for i in 0..20 {
+            cache.put(i, i);
+        }
+        let before: Vec<(u32, u32)> = cache.iter().map(|(k, v)| (*k, *v)).collect();
+        cache.maintain();
+        let after: Vec<(u32, u32)> = cache.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(before, after);
+        assert_eq!(cache.ghost.len(), 10);
+    }
+
+    #[test]
+    fn clear_resets_statistics() {
+        let mut cache = S3FIFOBuilder::new(100).hit_rate_window(4).build();
+        for i in 0..20 {
+            cache.put(i, i);
+        }
+        cache.get(&19);
+        assert_eq!(cache.scan_filter_ratio(), 1.0);
+        assert_eq!(cache.recent_hit_rate(), 1.0);
+
+        cache.clear();
+        assert_eq!(cache.scan_filter_ratio(), 0.0);
+        assert_eq!(cache.recent_hit_rate(), 0.0);
+
+        // The window keeps its size
+        cache.get(&0);
+        cache.put(0, 0);
+        cache.get(&0);
+        assert_eq!(cache.recent_hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn small_capacities_round_up_to_one() {
+        let mut cache = S3FIFO::new(5);
+        assert_eq!(cache.small_capacity, 1);
+        cache.put(0, 0);
+        cache.put(1, 1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.validate().is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn cannot_create_empty_main() {
+        let _: S3FIFO<u32, u32> = S3FIFO::with_capacities(2, 0, 2);
+    }
+
+    #[test]
+    fn size_estimate_counts_every_slot() {
+        let base: S3FIFO<u64, u64> = S3FIFO::with_capacities(10, 10, 10);
+        let main: S3FIFO<u64, u64> = S3FIFO::with_capacities(10, 20, 10);
+        let ghost: S3FIFO<u64, u64> = S3FIFO::with_capacities(10, 10, 20);
+        assert!(base.estimated_size_bytes() > core::mem::size_of::<S3FIFO<u64, u64>>());
+
+        let slots = main.main.capacity() - base.main.capacity();
+        assert!(slots >= 10);
+        assert_eq!(
+            main.estimated_size_bytes() - base.estimated_size_bytes(),
+            slots * core::mem::size_of::<Item<u64, u64>>()
+        );
+
+        let slots = ghost.ghost.capacity() - base.ghost.capacity();
+        assert!(slots >= 10);
+        assert_eq!(
+            ghost.estimated_size_bytes() - base.estimated_size_bytes(),
+            slots * core::mem::size_of::<Key<u64>>()
+        );
+    }
+
+    #[test]
+    fn reserve_keeps_capacities() {
+        let mut cache = S3FIFO::new(100);
+        cache.reserve(1000);
+        assert!(cache.small.capacity() >= 100);
+        assert!(cache.main.capacity() >= 900);
+        assert_eq!(cache.capacity(), 100);
+
+        // Reserving does not delay eviction
+        for i in 0..11 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.small.len(), 10);
+        assert_eq!(cache.ghost_keys().collect::<Vec<_>>(), vec![&0]);
+
+        // The split does not overflow for large requests
+        let large = usize::MAX / 4;
+        assert_eq!(cache.small_share(large), large / 10);
+    }
+
+    #[test]
+    fn can_drain_one_queue() {
+        let mut cache = S3FIFO::new(100);
+        cache.warm((0..3).map(|i| (i, i)));
+        for i in 3..15 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.ghost.len(), 2);
+
+        assert_eq!(cache.drain_small(), (5..15).rev().collect::<Vec<_>>());
+        assert!(cache.small.is_empty());
+        assert_eq!(cache.main.len(), 3);
+        assert_eq!(cache.ghost.len(), 2);
+
+        assert_eq!(cache.drain_main().len(), 3);
+        assert!(cache.main.is_empty());
+        assert_eq!(cache.ghost.len(), 2);
+    }
+
+    #[test]
+    fn can_drain_filter() {
+        let mut cache = S3FIFO::new(100);
+        for i in 0..10 {
+            cache.put(i, i);
+        }
+
+        let mut removed = cache.drain_filter(|k, _| k % 2 == 0);
+        removed.sort();
+        assert_eq!(removed, vec![(0, 0), (2, 2), (4, 4), (6, 6), (8, 8)]);
+        let keys: Vec<u32> = cache.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![9, 7, 5, 3, 1]);
+        assert!(cache.ghost.is_empty());
+    }
+
+    #[test]
+    fn can_promote() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 0);
+        assert_eq!(cache.promote(&0), Some(None));
+        assert_eq!(cache.promote(&0), None);
+        assert_eq!(cache.promote(&1), None);
+        assert_eq!(cache.main.len(), 1);
+        assert!(cache.small.is_empty());
+    }
+
+    #[test]
+    fn promote_returns_value_evicted_from_main() {
+        let mut cache = S3FIFO::with_capacities(2, 1, 2);
+        cache.put(0, "zero");
+        cache.put(1, "one");
+        assert_eq!(cache.promote(&0), Some(None));
+        assert_eq!(cache.promote(&1), Some(Some("zero")));
+        assert_eq!(cache.main.len(), 1);
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        assert!(cache.small.is_empty());
+    }
+
+    #[test]
+    fn can_list_ghost_keys() {
+        let mut cache = S3FIFO::new(10);
+        for i in 0..4 {
+            cache.put(i, i);
+        }
+        let ghost: Vec<&u32> = cache.ghost_keys().collect();
+        assert_eq!(ghost, vec![&2, &1, &0]);
+    }
+
+    #[test]
+    fn can_compare_caches() {
+        let mut a = S3FIFO::new(10);
+        let mut b = S3FIFO::new(10);
+        for i in 0..4 {
+            a.put(i, i);
+            b.put(i, i);
+        }
+        assert!(a == b);
+
+        a.get(&3);
+        assert!(a != b);
+        b.get(&3);
+        assert!(a == b);
+    }
+
+    #[test]
+    fn can_warm() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 0);
+        cache.warm((0..20).map(|i| (i, i)));
+
+        assert_eq!(cache.small.len(), 1);
+        assert_eq!(cache.main.len(), 9);
+        assert_eq!(cache.get(&19), Some(&19));
+        assert_eq!(cache.validate(), Ok(()));
+    }
+
+    #[test]
+    fn put_evicts_at_most_one() {
+        // If a put dropped more than the one value it returns, the counts below would not add up
+        let mut cache = S3FIFO::new(10);
+        let (mut inserted, mut evicted) = (0, 0);
+        for i in 0..1000 {
+            cache.get(&(i % 7));
+            if cache.get(&(i % 50)).is_none() {
+                inserted += 1;
+                if cache.put(i % 50, i).1.is_some() {
+                    evicted += 1;
+                }
+            }
+        }
+        assert_eq!(cache.small.len() + cache.main.len() + evicted, inserted);
+    }
+
+    #[test]
+    fn can_put_without_evicting() {
+        let mut cache = S3FIFO::new(20);
+        assert!(cache.try_put_no_evict(0, 0).is_ok());
+        assert!(cache.try_put_no_evict(1, 1).is_ok());
+        assert_eq!(cache.try_put_no_evict(2, 2), Err((2, 2)));
+        assert_eq!(cache.try_put_no_evict(1, 10), Ok(&mut 1));
+
+        // A ghost hit only needs room in main
+        cache.put(2, 2);
+        assert!(cache.try_put_no_evict(0, 0).is_ok());
+        assert_eq!(cache.main.len(), 1);
+
+        // A ghost hit into a full main is rejected even though small has room
+        let mut cache = S3FIFO::with_capacities(2, 1, 2);
+        cache.put(0, 0);
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.warm([(3, 3)]);
+        cache.pop();
+        assert!(cache.would_promote(&0));
+        assert_eq!(cache.try_put_no_evict(0, 0), Err((0, 0)));
+        assert_eq!(cache.validate(), Ok(()));
+    }
+
+    #[test]
+    fn can_count_frequencies() {
+        let mut cache = S3FIFO::new(100);
+        for i in 0..4 {
+            cache.put(i, i);
+            for _ in 0..i {
+                cache.get(&i);
+            }
+        }
+        cache.put(4, 4);
+        assert_eq!(cache.freq_histogram(), [2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn can_debug_cache() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, "zero");
+        cache.put(1, "one");
+        assert_eq!(
+            format!("{cache:?}"),
+            "S3FIFO { small: [Item { key: 1, value: \"one\", freq: 0, pinned: false, seq: 1 }], main: [], ghost: [Key { key: 0, freq: 0 }] }"
+        );
+    }
+
+    #[test]
+    fn can_bound_reinsertions() {
+        let mut cache = S3FIFOBuilder::new(10).max_reinsertions(2).build();
+        cache.warm((0..9).map(|i| (i, i)));
+        for i in 0..9 {
+            cache.get(&i);
+            cache.get(&i);
+        }
+
+        // Items 0 and 1 are reinserted, then item 2 is evicted despite its frequency
+        assert_eq!(cache.evict_main(), Some(2));
+        assert_eq!(cache.main.len(), 8);
+    }
+
+    #[test]
+    fn can_get_with_freq() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 0);
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 1)));
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 2)));
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 3)));
+        assert_eq!(cache.get_with_freq(&0), Some((&0, 3)));
+        assert_eq!(cache.get_with_freq(&1), None);
+    }
+
+    #[test]
+    fn can_iterate_by_eviction_order() {
+        let mut cache = S3FIFO::new(30);
+        cache.warm([(0, 0), (1, 1)]);
+        cache.get(&0);
+        for i in 2..5 {
+            cache.put(i, i);
+        }
+        cache.get(&2);
+        cache.get(&2);
+
+        let order: Vec<(u32, u8)> = cache
+            .iter_by_eviction_order()
+            .map(|(k, _, freq)| (*k, freq))
+            .collect();
+        assert_eq!(order, vec![(3, 0), (4, 0), (1, 1), (0, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn can_get_or_insert_with_status() {
+        let mut cache = S3FIFO::new(10);
+        assert_eq!(cache.get_or_insert_with_status(0, || 10), (&mut 10, false));
+        assert_eq!(cache.get_or_insert_with_status(0, || 20), (&mut 10, true));
+        assert_eq!(cache.small[0].freq.get(), 1);
+    }
+
+    #[test]
+    fn can_set_ghost_capacity() {
+        let mut cache = S3FIFO::new(10);
+        for i in 0..10 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.ghost.len(), 9);
+
+        cache.set_ghost_capacity(3);
+        let ghost: Vec<&u32> = cache.ghost_keys().collect();
+        assert_eq!(ghost, vec![&8, &7, &6]);
+
+        cache.put(10, 10);
+        assert_eq!(cache.ghost.len(), 3);
+        assert_eq!(cache.validate(), Ok(()));
+
+        cache.set_ghost_capacity(0);
+        cache.put(11, 11);
+        assert!(cache.ghost.is_empty());
+    }
+
+    #[test]
+    fn can_replace_if() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 10);
+        assert_eq!(cache.replace_if(&0, 20, |v| *v == 11), Err(20));
+        assert_eq!(cache.replace_if(&0, 20, |v| *v == 10), Ok(10));
+        assert_eq!(cache.replace_if(&1, 30, |_| true), Err(30));
+        assert_eq!(cache.get(&0), Some(&20));
+    }
+
+    #[test]
+    fn pinned_items_are_not_evicted() {
+        let mut cache = S3FIFO::new(20);
+        cache.put(0, 0);
+        cache.put(1, 1);
+        assert!(cache.pin(&0));
+        assert!(cache.pin(&1));
+        assert!(!cache.pin(&2));
+
+        // Small is full of pinned items, so it grows instead
+        assert_eq!(cache.put(2, 2).1, None);
+        assert_eq!(cache.small.len(), 3);
+        assert_eq!(cache.pop(), Some(2));
+        assert_eq!(cache.pop(), None);
+
+        cache.unpin(&0);
+        assert_eq!(cache.pop(), Some(0));
+        assert_eq!(cache.validate(), Ok(()));
+
+        // Main skips pinned items as well
+        cache.warm((10..28).map(|i| (i, i)));
+        cache.pin(&10);
+        assert_eq!(cache.evict_main(), Some(11));
+    }
+
+    #[test]
+    fn unpinned_overflow_is_valid() {
+        let mut cache = S3FIFO::new(20);
+        cache.put(0, 0);
+        cache.put(1, 1);
+        cache.pin(&0);
+        cache.pin(&1);
+        cache.put(2, 2);
+        cache.unpin(&0);
+        cache.unpin(&1);
+        assert_eq!(cache.small.len(), 3);
+        assert_eq!(cache.validate(), Ok(()));
+
+        // Small stays oversized as it evicts one item per put
+        assert_eq!(cache.put(3, 3).1, Some(0));
+        assert_eq!(cache.small.len(), 3);
+        assert_eq!(cache.validate(), Ok(()));
+
+        // A pinned item does not excuse growing any further
+        cache.pin(&1);
+        cache.small.push_front(Item {
+            key: 4,
+            value: 4,
+            freq: Cell::new(0),
+            pinned: false,
+            seq: 0,
+        });
+        assert!(cache.validate().is_err());
+    }
+
+    #[test]
+    fn can_set_frequency() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 0);
+        assert!(cache.set_frequency(&0, 200));
+        assert_eq!(cache.small[0].freq.get(), 3);
+        assert!(cache.set_frequency(&0, 0));
+        assert_eq!(cache.small[0].freq.get(), 0);
+        assert!(!cache.set_frequency(&1, 1));
+    }
+
+    #[test]
+    fn can_track_recent_hit_rate() {
+        let mut cache = S3FIFOBuilder::new(100).hit_rate_window(4).build();
+        assert_eq!(cache.recent_hit_rate(), 0.0);
+
+        cache.put(0, 0);
+        cache.get(&0);
+        cache.get(&1);
+        assert_eq!(cache.recent_hit_rate(), 0.5);
+
+        // Older outcomes fall out of the window
+        for _ in 0..4 {
+            cache.get(&0);
+        }
+        assert_eq!(cache.recent_hit_rate(), 1.0);
+        cache.get_or_insert_with_status(1, || 1);
+        assert_eq!(cache.recent_hit_rate(), 0.75);
+
+        // Touching is not a lookup
+        cache.touch(&1);
+        cache.touch(&2);
+        assert_eq!(cache.recent_hit_rate(), 0.75);
+    }
+
+    #[test]
+    fn peek_does_not_bump() {
+        let mut cache = S3FIFO::new(10);
+        cache.put(0, 0);
+        assert_eq!(cache.peek(&0), Some(&0));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.small[0].freq.get(), 0);
+    }
+
+    #[test]
+    fn seeded_keys_are_stable() {
+        let key = S3FIFOKey::new_seeded(&Abc { a: 1, b: 2, c: 3 }, 7);
+        let other = S3FIFOKey::new_seeded(&Abc { a: 1, b: 2, c: 3 }, 7);
+        assert!(key == other);
+        assert!(key != S3FIFOKey::new_seeded(&Abc { a: 1, b: 2, c: 3 }, 8));
+        assert!(key != S3FIFOKey::new_seeded(&Abc { a: 1, b: 2, c: 4 }, 7));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn keys_can_index_maps() {
+        let mut map = std::collections::HashMap::new();
+        let value = Abc { a: 1, b: 2, c: 3 };
+        map.insert(S3FIFOKey::new(&value), "metadata");
+        assert_eq!(map.get(&S3FIFOKey::new(&value)), Some(&"metadata"));
+    }
+
+    #[test]
+    fn keys_with_custom_fingerprint() {
+        let fingerprint = |v: &Abc| (v.a as u128) << 64 | (v.b as u128) << 32 | v.c as u128;
+        let mut cache = S3FIFO::new(100);
+        let value = Abc { a: 1, b: 2, c: 3 };
+        let key = S3FIFOKey::with_fingerprint(&value, fingerprint);
+        cache.put(key, value);
+
+        let same = S3FIFOKey::with_fingerprint(&Abc { a: 1, b: 2, c: 3 }, fingerprint);
+        let other = S3FIFOKey::with_fingerprint(&Abc { a: 1, b: 2, c: 4 }, fingerprint);
+        assert!(cache.get(&same).is_some());
+        assert!(cache.get(&other).is_none());
+        assert_eq!(format!("{}", same), "0x10000000200000003");
+    }
+
+    #[test]
+    fn ghost_remembers_key_once() {
+        let mut cache = S3FIFO::new(100);
+        cache.put(0, 0);
+        cache.get(&0);
+        for i in 1..11 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.ghost.iter().filter(|k| k.key == 0).count(), 1);
+
+        // Admit the key to small again behind ghost's back and evict it a second time
+        cache.small.push_back(Item {
+            key: 0,
+            value: 0,
+            freq: Cell::new(0),
+            pinned: false,
+            seq: 0,
+        });
+        cache.put(11, 11);
+        assert_eq!(cache.ghost.iter().filter(|k| k.key == 0).count(), 1);
+        assert_eq!(cache.ghost[0].key, 0);
+        assert_eq!(cache.ghost[0].freq.get(), 1);
+    }
+
+    #[test]
+    fn evict_main_with_corrupted_freq() {
+        let mut cache = S3FIFO::new(10);
+        cache.warm((0..9).map(|i| (i, i)));
+        for item in cache.main.iter() {
+            item.freq.set(100);
+        }
+        cache.pin(&0);
+
+        assert_eq!(cache.evict_main(), Some(1));
+        assert_eq!(cache.main.len(), 8);
+    }
+
+    #[test]
+    fn put_reports_outcome() {
+        let mut cache = S3FIFO::new(10);
+        assert_eq!(cache.put_with_outcome(0, 0).2, PutOutcome::AdmittedToSmall);
+        assert_eq!(cache.put_with_outcome(0, 0).2, PutOutcome::Updated);
+        cache.put(1, 1);
+        assert_eq!(
+            cache.put_with_outcome(0, 0).2,
+            PutOutcome::PromotedFromGhost
+        );
+    }
 }
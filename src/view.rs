@@ -0,0 +1,70 @@
+use crate::{Iter, S3FIFO};
+
+///
+/// A read-only handle to an S3FIFO that cannot change what is evicted next.
+///
+/// Unlike `S3FIFO::get`, none of these methods increment frequencies or record
+/// lookups, so the view can be handed to metrics or debugging code without
+/// perturbing the cache.
+///
+/// ```
+/// use s3_fifo::S3FIFO;
+///
+/// let mut cache = S3FIFO::new(100);
+/// cache.put(1, "one");
+///
+/// let view = cache.view();
+/// assert_eq!(view.peek(&1), Some(&"one"));
+/// assert!(view.contains_key(&1));
+/// assert_eq!(view.len(), 1);
+/// ```
+///
+pub struct S3FIFOView<'a, K, V> {
+    cache: &'a S3FIFO<K, V>,
+}
+
+impl<'a, K: PartialEq + Clone, V> S3FIFOView<'a, K, V> {
+    pub(crate) fn new(cache: &'a S3FIFO<K, V>) -> Self {
+        S3FIFOView { cache }
+    }
+
+    /// Read an item from the cache without incrementing its frequency.
+    pub fn peek(&self, key: &K) -> Option<&'a V> {
+        self.cache.peek(key)
+    }
+
+    /// Whether the key is in small or main.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    /// The number of items in small and main.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Whether small and main are both empty.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Iterate over the entries in the cache without changing their frequency.
+    pub fn iter(&self) -> Iter<'a, K, V> {
+        self.cache.iter()
+    }
+
+    /// Iterate over the keys in ghost, from the most to the least recently evicted.
+    pub fn ghost_keys(&self) -> impl Iterator<Item = &'a K> {
+        self.cache.ghost_keys()
+    }
+
+    /// Count the items in small and main at each frequency from 0 to 3.
+    pub fn freq_histogram(&self) -> [usize; 4] {
+        self.cache.freq_histogram()
+    }
+
+    /// The fraction of recent lookups that found their key.
+    pub fn recent_hit_rate(&self) -> f64 {
+        self.cache.recent_hit_rate()
+    }
+}
@@ -0,0 +1,64 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::Cell;
+
+///
+/// A fixed-size ring of the most recent lookup outcomes.
+///
+/// The cells allow recording through a shared reference, as `get` takes `&self`.
+///
+pub(crate) struct HitWindow {
+    outcomes: Box<[Cell<bool>]>,
+    next: Cell<usize>,
+    len: Cell<usize>,
+    hits: Cell<usize>,
+}
+
+impl HitWindow {
+    /// Create a window over the last `size` outcomes, which records nothing if `size` is zero.
+    pub(crate) fn new(size: usize) -> Self {
+        HitWindow {
+            outcomes: (0..size)
+                .map(|_| Cell::new(false))
+                .collect::<Vec<_>>()
+                .into(),
+            next: Cell::new(0),
+            len: Cell::new(0),
+            hits: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, hit: bool) {
+        let size = self.outcomes.len();
+        if size == 0 {
+            return;
+        }
+        let next = self.next.get();
+        if self.len.get() == size {
+            // Overwrite the oldest outcome
+            if self.outcomes[next].get() {
+                self.hits.set(self.hits.get() - 1);
+            }
+        } else {
+            self.len.set(self.len.get() + 1);
+        }
+        self.outcomes[next].set(hit);
+        if hit {
+            self.hits.set(self.hits.get() + 1);
+        }
+        self.next.set((next + 1) % size);
+    }
+
+    /// Forget every recorded outcome, keeping the size of the window.
+    pub(crate) fn clear(&mut self) {
+        self.next.set(0);
+        self.len.set(0);
+        self.hits.set(0);
+    }
+
+    pub(crate) fn hit_rate(&self) -> f64 {
+        match self.len.get() {
+            0 => 0.0,
+            len => self.hits.get() as f64 / len as f64,
+        }
+    }
+}